@@ -1,89 +1,425 @@
 // src/main.rs
 use std::env;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::thread;
 use std::collections::VecDeque;
 
 use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::codecs::{CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
-use symphonia::core::formats::FormatOptions;
+use symphonia::core::formats::{FormatOptions, FormatReader};
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
-use symphonia::core::units::Time;
+use symphonia::core::units::{Time, TimeBase};
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rand::seq::SliceRandom;
 use single_instance::SingleInstance;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, MediaKeyCode},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 
-struct AudioPlayer {
-    file_path: String,
-    is_paused: Arc<Mutex<bool>>,
-    should_stop: Arc<Mutex<bool>>,
-    seek_position: Arc<Mutex<Option<f64>>>,
-    volume: Arc<Mutex<f32>>,
-    current_time: Arc<Mutex<f64>>,  // 当前播放位置（秒）
+// 音量每次調整的步進百分比
+const VOLUME_STEP: u32 = 5;
+
+// 目錄展開時視為音訊檔案的副檔名
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "wav", "ogg", "m4a", "aac", "wma", "opus"];
+
+// 連續解碼失敗超過這個次數就放棄目前曲目，避免在損壞的檔案上無限重試
+const MAX_DECODE_ERRORS: u32 = 3;
+
+// 把 0..=100 的音量百分比映射成非線性的放大係數，
+// 讓滑桿/按鍵的每一步聽起來音量變化均勻（人耳對響度的感知是對數式的）。
+fn volume_to_gain(vol_percent: u32) -> f32 {
+    (vol_percent as f32 / 100.0).powi(2)
 }
 
-impl AudioPlayer {
-    fn new(file_path: String) -> Self {
+// 計算最大公約數，用於把輸入/輸出採樣率約分成最簡整數比
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+// 把秒數格式化成 mm:ss，用於顯示播放位置/總長度
+fn format_time(seconds: f64) -> String {
+    let total = seconds.max(0.0).round() as u64;
+    format!("{:02}:{:02}", total / 60, total % 60)
+}
+
+// 把命令行參數展開成實際的音訊檔案清單：檔案直接收錄，
+// 目錄則（不遞迴）收錄其中副檔名受支援的音訊檔案，並按檔名排序。
+fn collect_audio_paths(args: &[String]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for arg in args {
+        let p = PathBuf::from(arg);
+        if p.is_dir() {
+            if let Ok(entries) = std::fs::read_dir(&p) {
+                let mut dir_files: Vec<PathBuf> = entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|f| {
+                        f.extension()
+                            .and_then(|ext| ext.to_str())
+                            .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+                            .unwrap_or(false)
+                    })
+                    .collect();
+                dir_files.sort();
+                paths.extend(dir_files);
+            }
+        } else {
+            paths.push(p);
+        }
+    }
+    paths
+}
+
+/// 在解碼器與環形緩衝區之間做逐聲道線性插值重採樣，
+/// 讓輸入採樣率與輸出設備採樣率不一致時音高仍然正確。
+struct Resampler {
+    ratio: f64,
+    pos: f64,
+    channels: usize,
+    current_frame: Vec<f32>,
+    next_frame: Vec<f32>,
+    initialized: bool,
+}
+
+impl Resampler {
+    fn new(input_rate: u32, output_rate: u32, channels: usize) -> Self {
+        let g = gcd(input_rate, output_rate).max(1);
+        let ratio = (input_rate / g) as f64 / (output_rate / g) as f64;
         Self {
-            file_path,
-            is_paused: Arc::new(Mutex::new(false)),
-            should_stop: Arc::new(Mutex::new(false)),
-            seek_position: Arc::new(Mutex::new(None)),
-            volume: Arc::new(Mutex::new(1.0)),
-            current_time: Arc::new(Mutex::new(0.0)),
+            ratio,
+            pos: 0.0,
+            channels,
+            current_frame: vec![0.0; channels],
+            next_frame: vec![0.0; channels],
+            initialized: false,
         }
     }
 
-    fn play(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let file = File::open(&self.file_path)?;
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    /// 跳轉（seek）後呼叫：丟棄插值用的前後幀與累積的小數位置，
+    /// 讓重採樣器從新位置的第一個 packet 重新開始，避免插值到跳轉前的舊樣本。
+    fn reset(&mut self) {
+        self.pos = 0.0;
+        self.initialized = false;
+    }
+
+    /// `frames` 必須已經是交錯格式、聲道數等於 `output_channels`。
+    /// 產生的樣本直接追加進 `out`。
+    fn process(&mut self, frames: &[f32], out: &mut VecDeque<f32>) {
+        let channels = self.channels;
+        if channels == 0 {
+            return;
+        }
+        let frame_count = frames.len() / channels;
+        let mut idx = 0;
+
+        if !self.initialized {
+            if frame_count == 0 {
+                return;
+            }
+            self.current_frame.copy_from_slice(&frames[0..channels]);
+            self.next_frame.copy_from_slice(&frames[0..channels]);
+            idx = 1;
+            self.initialized = true;
+        }
+
+        loop {
+            while self.pos < 1.0 {
+                for c in 0..channels {
+                    out.push_back(lerp(self.current_frame[c], self.next_frame[c], self.pos as f32));
+                }
+                self.pos += self.ratio;
+            }
+            while self.pos >= 1.0 {
+                if idx >= frame_count {
+                    // 這個 packet 已經用完：保留最後一幀與小數位置，下個 packet 從它的第一幀接著插值
+                    return;
+                }
+                self.pos -= 1.0;
+                self.current_frame.copy_from_slice(&self.next_frame);
+                self.next_frame.copy_from_slice(&frames[idx * channels..(idx + 1) * channels]);
+                idx += 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RepeatMode {
+    Off,
+    All,
+    One,
+}
+
+impl RepeatMode {
+    fn label(&self) -> &'static str {
+        match self {
+            RepeatMode::Off => "關閉",
+            RepeatMode::All => "全部重複",
+            RepeatMode::One => "單曲重複",
+        }
+    }
+}
 
-        let mut hint = Hint::new();
-        if let Some(ext) = std::path::Path::new(&self.file_path).extension() {
-            hint.with_extension(ext.to_str().unwrap());
+enum SkipDirection {
+    Next,
+    Previous,
+}
+
+enum TrackOutcome {
+    Finished,
+    Stopped,
+    Skipped(SkipDirection),
+}
+
+/// 開啟並探測單一曲目所需的狀態：檔案是否存在、容器格式是否能探測、
+/// 解碼器是否支援都在這裡一次決定，跟 `decode_track` 裡逐 packet 的解碼迴圈分開，
+/// 這樣呼叫端可以把「這個檔案根本放不了」跟「放到一半解碼失敗」分別處理。
+struct TrackSetup {
+    format: Box<dyn FormatReader>,
+    track_id: u32,
+    codec_params: CodecParameters,
+    decoder: Box<dyn Decoder>,
+    input_sample_rate: u32,
+    input_channels: usize,
+    time_base: Option<TimeBase>,
+    total_duration: Option<f64>,
+}
+
+// 開檔、探測容器格式、找音頻軌道、建立解碼器。
+// 任何一步失敗都代表這個檔案放不了（損壞、格式不支援、非音訊檔等），
+// 呼叫端應該把它當成可跳過的單一曲目錯誤，而不是讓整個播放佇列中止。
+fn open_track(path: &Path) -> Result<TrackSetup, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let meta_opts = MetadataOptions::default();
+    let fmt_opts = FormatOptions::default();
+
+    let probed = symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts)?;
+    let format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or("找不到音頻軌道")?;
+
+    let track_id = track.id;
+    // 保留自己的一份 codec_params，這樣 ResetRequired 時才能在不重新借用 track 的情況下重建解碼器
+    let codec_params = track.codec_params.clone();
+    let decoder = symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
+
+    let input_sample_rate = *codec_params.sample_rate.as_ref().ok_or("無法獲取採樣率")?;
+    let input_channels = codec_params.channels.as_ref().ok_or("無法獲取聲道信息")?.count();
+
+    // 用來把 packet 的時間戳轉換成秒數，取代容易隨著 seek/變動的 packet 大小而產生誤差的累加估算
+    let time_base = codec_params.time_base;
+    let total_duration = codec_params.n_frames.map(|n| n as f64 / input_sample_rate as f64);
+
+    Ok(TrackSetup {
+        format,
+        track_id,
+        codec_params,
+        decoder,
+        input_sample_rate,
+        input_channels,
+        time_base,
+        total_duration,
+    })
+}
+
+/// 從按鍵處理迴圈送往解碼執行緒的指令。
+enum PlayerCommand {
+    Pause,
+    Resume,
+    SeekTo(f64),
+    SeekRelative(f64),
+    SetVolume(f32),
+    Next,
+    Previous,
+    Stop,
+}
+
+/// 解碼執行緒回報給 UI 的狀態，取代原本分散在各處的 `println!`。
+enum PlayerStatus {
+    Position(f64),
+    TrackChanged(String),
+    // 換曲目時附帶的輸入/輸出格式與總長度說明，與 TrackChanged 分開送以維持每個變體職責單一
+    TrackInfo(String),
+    Finished,
+    Error(String),
+}
+
+/// 播放佇列：追蹤曲目清單、目前位置與重複/隨機播放模式。
+/// `order` 是 `tracks` 的索引排列，開啟隨機播放時會被打亂。
+struct Playlist {
+    tracks: Vec<PathBuf>,
+    order: Vec<usize>,
+    current: usize,
+    repeat: RepeatMode,
+    shuffle: bool,
+}
+
+impl Playlist {
+    fn new(tracks: Vec<PathBuf>) -> Self {
+        let order = (0..tracks.len()).collect();
+        Self {
+            tracks,
+            order,
+            current: 0,
+            repeat: RepeatMode::Off,
+            shuffle: false,
         }
+    }
 
-        let meta_opts = MetadataOptions::default();
-        let fmt_opts = FormatOptions::default();
+    fn current_path(&self) -> Option<PathBuf> {
+        self.order.get(self.current).and_then(|&i| self.tracks.get(i)).cloned()
+    }
 
-        let probed = symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts)?;
-        let mut format = probed.format;
+    fn toggle_repeat(&mut self) -> RepeatMode {
+        self.repeat = match self.repeat {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        };
+        self.repeat
+    }
 
-        let track = format
-            .tracks()
-            .iter()
-            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .ok_or("找不到音頻軌道")?;
+    fn toggle_shuffle(&mut self) -> bool {
+        self.shuffle = !self.shuffle;
+        let playing = self.order.get(self.current).copied();
+        if self.shuffle {
+            self.order.shuffle(&mut rand::thread_rng());
+        } else {
+            self.order = (0..self.tracks.len()).collect();
+        }
+        // 切換排序後仍然指向同一首曲目
+        if let Some(idx) = playing {
+            if let Some(pos) = self.order.iter().position(|&i| i == idx) {
+                self.current = pos;
+            }
+        }
+        self.shuffle
+    }
+
+    // 自然播完一首後前進；單曲重複時留在原地重播，其餘情況委派給 advance_unconditional
+    fn advance(&mut self) -> bool {
+        if self.order.is_empty() {
+            return false;
+        }
+        if self.repeat == RepeatMode::One {
+            return true;
+        }
+        self.advance_unconditional()
+    }
+
+    // 無視「單曲重複」，一律前進到下一首；供使用者手動按下一首/媒體鍵時使用，
+    // 否則在單曲重複模式下按下一首會變成重播同一首，跟 retreat() 的行為不對稱
+    fn advance_unconditional(&mut self) -> bool {
+        if self.order.is_empty() {
+            return false;
+        }
+        if self.current + 1 < self.order.len() {
+            self.current += 1;
+            true
+        } else if self.repeat == RepeatMode::All {
+            self.current = 0;
+            true
+        } else {
+            false
+        }
+    }
 
-        let track_id = track.id;
-        let mut decoder = symphonia::default::get_codecs()
-            .make(&track.codec_params, &DecoderOptions::default())?;
+    fn retreat(&mut self) {
+        if self.order.is_empty() {
+            return;
+        }
+        if self.current > 0 {
+            self.current -= 1;
+        } else if self.repeat == RepeatMode::All {
+            self.current = self.order.len() - 1;
+        }
+    }
+}
+
+struct AudioPlayer {
+    playlist: Arc<Mutex<Playlist>>,
+    // 只在這裡的解碼執行緒與 CPAL 的即時音頻回呼之間共享，控制迴圈一律透過指令通道操作它們
+    is_paused: Arc<Mutex<bool>>,
+    volume: Arc<Mutex<u32>>, // 音量百分比（0..=100）
+    commands: Receiver<PlayerCommand>,
+    status: Sender<PlayerStatus>,
+}
+
+impl AudioPlayer {
+    fn new(playlist: Playlist, commands: Receiver<PlayerCommand>, status: Sender<PlayerStatus>) -> Self {
+        Self {
+            playlist: Arc::new(Mutex::new(playlist)),
+            is_paused: Arc::new(Mutex::new(false)),
+            volume: Arc::new(Mutex::new(100)),
+            commands,
+            status,
+        }
+    }
 
-        let input_sample_rate = *track.codec_params.sample_rate.as_ref().ok_or("無法獲取採樣率")?;
-        let input_channels = track.codec_params.channels.as_ref().ok_or("無法獲取聲道信息")?.count();
+    // 一次性把目前排隊的指令全部套用；若收到 Next/Previous/Stop 則直接回傳對應的 TrackOutcome
+    fn drain_commands(
+        &mut self,
+        pending_seek: &mut Option<f64>,
+        current_time: f64,
+        total_duration: Option<f64>,
+    ) -> Option<TrackOutcome> {
+        let clamp_seek = |target: f64| -> f64 {
+            let target = target.max(0.0);
+            match total_duration {
+                Some(max) => target.min(max),
+                None => target,
+            }
+        };
+        while let Ok(cmd) = self.commands.try_recv() {
+            match cmd {
+                PlayerCommand::Pause => *self.is_paused.lock().unwrap() = true,
+                PlayerCommand::Resume => *self.is_paused.lock().unwrap() = false,
+                PlayerCommand::SeekTo(pos) => *pending_seek = Some(clamp_seek(pos)),
+                PlayerCommand::SeekRelative(delta) => *pending_seek = Some(clamp_seek(current_time + delta)),
+                PlayerCommand::SetVolume(vol) => *self.volume.lock().unwrap() = vol.clamp(0.0, 100.0) as u32,
+                PlayerCommand::Next => return Some(TrackOutcome::Skipped(SkipDirection::Next)),
+                PlayerCommand::Previous => return Some(TrackOutcome::Skipped(SkipDirection::Previous)),
+                PlayerCommand::Stop => return Some(TrackOutcome::Stopped),
+            }
+        }
+        None
+    }
 
-        // 初始化 CPAL 音頻輸出
+    fn play(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // 初始化 CPAL 音頻輸出，在整個播放佇列期間保持開啟以達到近似無縫切換
         let host = cpal::default_host();
         let device = host.default_output_device().ok_or("找不到輸出設備")?;
         let config = device.default_output_config()?;
-        
+
         let output_sample_rate = config.sample_rate().0;
         let output_channels = config.channels() as usize;
-        
-        println!("\n輸入: {}Hz, {} 聲道", input_sample_rate, input_channels);
-        println!("輸出: {}Hz, {} 聲道\n", output_sample_rate, output_channels);
 
         let sample_buffer: Arc<Mutex<VecDeque<f32>>> = Arc::new(Mutex::new(VecDeque::with_capacity(48000 * 2)));
-        
+
         // 为闭包克隆引用
         let is_paused_clone = Arc::clone(&self.is_paused);
         let volume_clone = Arc::clone(&self.volume);
@@ -94,8 +430,9 @@ impl AudioPlayer {
             &config.config(),
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
                 let paused = *is_paused_clone.lock().unwrap();
-                let vol = *volume_clone.lock().unwrap();
-                
+                let vol_percent = *volume_clone.lock().unwrap();
+                let gain = volume_to_gain(vol_percent);
+
                 if paused {
                     for sample in data.iter_mut() {
                         *sample = 0.0;
@@ -105,7 +442,7 @@ impl AudioPlayer {
 
                 let mut buffer = sample_buffer_clone.lock().unwrap();
                 for sample in data.iter_mut() {
-                    *sample = buffer.pop_front().unwrap_or(0.0) * vol;
+                    *sample = buffer.pop_front().unwrap_or(0.0) * gain;
                 }
             },
             |err| eprintln!("音頻流錯誤: {}", err),
@@ -114,10 +451,106 @@ impl AudioPlayer {
 
         stream.play()?;
 
+        loop {
+            let path = match self.playlist.lock().unwrap().current_path() {
+                Some(p) => p,
+                None => break,
+            };
+
+            // decode_track 出錯（例如這首解到一半才冒出來、沒被它自己吞掉的錯誤）
+            // 只代表這一首放不了，跳到下一首繼續，不讓整個播放佇列中止
+            let outcome = match self.decode_track(&path, output_sample_rate, output_channels, &sample_buffer) {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    self.status
+                        .send(PlayerStatus::Error(format!("無法播放 {}: {}", path.display(), e)))
+                        .ok();
+                    TrackOutcome::Skipped(SkipDirection::Next)
+                }
+            };
+
+            match outcome {
+                TrackOutcome::Finished => {
+                    if !self.playlist.lock().unwrap().advance() {
+                        break;
+                    }
+                }
+                // 明確要求「下一首」（手動按鍵、媒體鍵，或曲目放不了而跳過）：
+                // 一律前進，不要被單曲重複卡在原地
+                TrackOutcome::Skipped(SkipDirection::Next) => {
+                    if !self.playlist.lock().unwrap().advance_unconditional() {
+                        break;
+                    }
+                }
+                TrackOutcome::Skipped(SkipDirection::Previous) => {
+                    self.playlist.lock().unwrap().retreat();
+                }
+                TrackOutcome::Stopped => break,
+            }
+        }
+
+        drop(stream);
+        self.status.send(PlayerStatus::Finished).ok();
+        Ok(())
+    }
+
+    // 解碼並播放單一曲目，直到播完、被要求停止，或被切到上一首/下一首為止
+    fn decode_track(
+        &mut self,
+        path: &Path,
+        output_sample_rate: u32,
+        output_channels: usize,
+        sample_buffer: &Arc<Mutex<VecDeque<f32>>>,
+    ) -> Result<TrackOutcome, Box<dyn std::error::Error>> {
+        // 開檔/探測/建立解碼器任何一步失敗都視為這個檔案放不了，跳到下一首，
+        // 不讓一個損壞或非音訊檔案中止整個播放佇列
+        let TrackSetup {
+            mut format,
+            track_id,
+            codec_params,
+            mut decoder,
+            input_sample_rate,
+            input_channels,
+            time_base,
+            total_duration,
+        } = match open_track(path) {
+            Ok(setup) => setup,
+            Err(e) => {
+                self.status
+                    .send(PlayerStatus::Error(format!("無法播放 {}: {}", path.display(), e)))
+                    .ok();
+                return Ok(TrackOutcome::Skipped(SkipDirection::Next));
+            }
+        };
+
+        self.status
+            .send(PlayerStatus::TrackChanged(path.display().to_string()))
+            .ok();
+
+        // 輸入/輸出格式與總長度也走 PlayerStatus，不要在解碼執行緒裡直接 println!，
+        // 否則會繞過這次重構建立的 UI 邊界，還會在終端機的 raw mode 下跟其他輸出疊在一起
+        let mut info = format!(
+            "輸入: {}Hz, {} 聲道\n輸出: {}Hz, {} 聲道",
+            input_sample_rate, input_channels, output_sample_rate, output_channels
+        );
+        if let Some(dur) = total_duration {
+            info.push_str(&format!("\n總長度: {}", format_time(dur)));
+        }
+        self.status.send(PlayerStatus::TrackInfo(info)).ok();
+
+        let mut current_time = 0.0_f64;
+        let mut last_reported_time = 0.0_f64;
+        let mut pending_seek: Option<f64> = None;
+
+        let mut resampler = Resampler::new(input_sample_rate, output_sample_rate, output_channels);
+        let mut converted: Vec<f32> = Vec::new();
+        let mut consecutive_errors: u32 = 0;
+
         // 解碼循環
         loop {
-            if *self.should_stop.lock().unwrap() {
-                break;
+            if let Some(outcome) = self.drain_commands(&mut pending_seek, current_time, total_duration) {
+                sample_buffer.lock().unwrap().clear();
+                return Ok(outcome);
             }
 
             // 檢查是否正在暫停，暫停時不解碼
@@ -127,25 +560,31 @@ impl AudioPlayer {
             }
 
             // 檢查是否需要跳轉
-            if let Some(pos) = self.seek_position.lock().unwrap().take() {
+            if let Some(pos) = pending_seek.take() {
                 let time = Time::from(pos);
                 if let Err(e) = format.seek(
                     symphonia::core::formats::SeekMode::Accurate,
                     symphonia::core::formats::SeekTo::Time { time, track_id: Some(track_id) },
                 ) {
-                    eprintln!("跳轉失敗: {}", e);
+                    self.status.send(PlayerStatus::Error(format!("跳轉失敗: {}", e))).ok();
                 } else {
-                    // 更新当前播放位置
-                    *self.current_time.lock().unwrap() = pos;
+                    // 先顯示目標位置，下一個 packet 的時間戳會接著校正成真正的位置
+                    current_time = pos;
+                    last_reported_time = pos;
+                    self.status.send(PlayerStatus::Position(current_time)).ok();
+                    // 重設重採樣器，避免用跳轉前殘留的插值幀去接新位置的樣本
+                    resampler.reset();
                 }
             }
 
             let packet = match format.next_packet() {
                 Ok(packet) => packet,
-                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(TrackOutcome::Finished);
+                }
                 Err(e) => {
-                    eprintln!("讀取包錯誤: {}", e);
-                    break;
+                    self.status.send(PlayerStatus::Error(format!("讀取包錯誤: {}", e))).ok();
+                    return Ok(TrackOutcome::Finished);
                 }
             };
 
@@ -153,88 +592,121 @@ impl AudioPlayer {
                 continue;
             }
 
+            // 用 packet 自帶的時間戳推出播放位置，而不是靠累加樣本數估算
+            if let Some(tb) = time_base {
+                let t = tb.calc_time(packet.ts());
+                current_time = t.seconds as f64 + t.frac;
+                if (current_time - last_reported_time).abs() >= 0.5 {
+                    last_reported_time = current_time;
+                    self.status.send(PlayerStatus::Position(current_time)).ok();
+                }
+            }
+
             match decoder.decode(&packet) {
                 Ok(decoded) => {
+                    consecutive_errors = 0;
+
                     let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
                     buf.copy_interleaved_ref(decoded);
-                    
+
                     // 检查缓冲区并等待消费
                     loop {
                         let sample_buf = sample_buffer.lock().unwrap();
-                        
+
                         if sample_buf.len() <= output_sample_rate as usize * output_channels * 2 {
                             // 缓冲区不大，可以添加数据
                             break;
                         }
-                        
+
                         // 缓冲区太大，释放锁并等待
                         drop(sample_buf);
                         std::thread::sleep(Duration::from_millis(5));
-                        
-                        // 检查是否需要停止
-                        if *self.should_stop.lock().unwrap() {
-                            return Ok(());
+
+                        // 检查是否需要停止或切换曲目
+                        if let Some(outcome) = self.drain_commands(&mut pending_seek, current_time, total_duration) {
+                            sample_buffer.lock().unwrap().clear();
+                            return Ok(outcome);
                         }
-                        
+
                         // 检查是否跳转，如果是则清空缓冲区
-                        if self.seek_position.lock().unwrap().is_some() {
-                            let mut buf = sample_buffer.lock().unwrap();
-                            buf.clear();
+                        if pending_seek.is_some() {
+                            sample_buffer.lock().unwrap().clear();
                             break;
                         }
                     }
-                    
+
                     // 重新获取锁以添加样本
                     let mut sample_buf = sample_buffer.lock().unwrap();
                     let samples = buf.samples();
-                    
-                    // 计算此包的时长并更新当前时间
-                    let duration_seconds = samples.len() as f64 / (input_sample_rate as f64 * input_channels as f64);
-                    *self.current_time.lock().unwrap() += duration_seconds;
-                    
-                    // 声道转换
+
+                    // 声道转换（先转换声道数，再交给重采样器处理采样率）
+                    converted.clear();
                     if input_channels == output_channels {
                         // 声道数相同，直接复制
-                        sample_buf.extend(samples.iter());
+                        converted.extend(samples.iter());
                     } else {
                         let frame_count = samples.len() / input_channels;
-                        
+
                         for i in 0..frame_count {
                             let frame_start = i * input_channels;
-                            
+
                             match (input_channels, output_channels) {
                                 (1, 2) => {
                                     // 单声道 -> 立体声
                                     let mono = samples[frame_start];
-                                    sample_buf.push_back(mono);
-                                    sample_buf.push_back(mono);
+                                    converted.push(mono);
+                                    converted.push(mono);
                                 }
                                 (2, 1) => {
                                     // 立体声 -> 单声道
-                                    sample_buf.push_back((samples[frame_start] + samples[frame_start + 1]) / 2.0);
+                                    converted.push((samples[frame_start] + samples[frame_start + 1]) / 2.0);
                                 }
                                 _ if input_channels >= output_channels => {
                                     // 多声道 -> 少声道
                                     for ch in 0..output_channels {
-                                        sample_buf.push_back(samples[frame_start + ch]);
+                                        converted.push(samples[frame_start + ch]);
                                     }
                                 }
                                 _ => {
                                     // 少声道 -> 多声道
                                     for _ in 0..output_channels {
-                                        sample_buf.push_back(samples[frame_start]);
+                                        converted.push(samples[frame_start]);
                                     }
                                 }
                             }
                         }
                     }
+
+                    // 重采样：把 input_sample_rate 的帧插值成 output_sample_rate 的帧
+                    resampler.process(&converted, &mut sample_buf);
+                }
+                Err(Error::ResetRequired) => {
+                    // 串流中途的取樣率/聲道數等參數改變了，用原本的 codec_params 重建解碼器繼續播放
+                    match symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default()) {
+                        Ok(new_decoder) => {
+                            decoder = new_decoder;
+                            consecutive_errors = 0;
+                        }
+                        Err(e) => {
+                            self.status
+                                .send(PlayerStatus::Error(format!("重建解碼器失敗: {}", e)))
+                                .ok();
+                            return Ok(TrackOutcome::Finished);
+                        }
+                    }
+                }
+                Err(e) => {
+                    consecutive_errors += 1;
+                    self.status.send(PlayerStatus::Error(format!("解碼錯誤: {}", e))).ok();
+                    if consecutive_errors > MAX_DECODE_ERRORS {
+                        self.status
+                            .send(PlayerStatus::Error("連續解碼錯誤過多，跳過此曲目".to_string()))
+                            .ok();
+                        return Ok(TrackOutcome::Skipped(SkipDirection::Next));
+                    }
                 }
-                Err(e) => eprintln!("解碼錯誤: {}", e),
             }
         }
-
-        drop(stream);
-        Ok(())
     }
 }
 
@@ -246,22 +718,28 @@ fn main() {
         std::process::exit(1);
     }
 
-    // 獲取命令行參數
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: cargo run -- <audio_file_path>");
+    // 獲取命令行參數（可以是多個檔案和/或目錄）
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("Usage: cargo run -- <audio_file_or_dir> [more_files_or_dirs...]");
         std::process::exit(1);
     }
 
-    let file_path = args[1].clone();
+    let tracks = collect_audio_paths(&args);
+    if tracks.is_empty() {
+        eprintln!("沒有找到任何可播放的音訊檔案。");
+        std::process::exit(1);
+    }
+
+    let playlist = Playlist::new(tracks);
+
+    // 指令通道：按鍵處理迴圈 -> 解碼執行緒；狀態通道：解碼執行緒 -> 按鍵處理迴圈（UI）
+    let (cmd_tx, cmd_rx) = mpsc::channel::<PlayerCommand>();
+    let (status_tx, status_rx) = mpsc::channel::<PlayerStatus>();
 
     // 創建播放器
-    let mut player = AudioPlayer::new(file_path);
-    let player_paused = Arc::clone(&player.is_paused);
-    let player_stop = Arc::clone(&player.should_stop);
-    let player_seek = Arc::clone(&player.seek_position);
-    let player_volume = Arc::clone(&player.volume);
-    let player_time = Arc::clone(&player.current_time);
+    let mut player = AudioPlayer::new(playlist, cmd_rx, status_tx);
+    let player_playlist = Arc::clone(&player.playlist);
 
     // 在新線程中播放
     let play_thread = thread::spawn(move || {
@@ -270,6 +748,10 @@ fn main() {
         }
     });
 
+    // UI 端維護的鏡像狀態，僅用於決定下一步要送出的指令與要顯示的文字
+    let mut paused = false;
+    let mut volume_percent: u32 = 100;
+
     // 啟用終端原始模式
     enable_raw_mode().unwrap();
 
@@ -280,18 +762,28 @@ fn main() {
     println!("  [→] - 前進 5 秒");
     println!("  [↑] - 音量增加");
     println!("  [↓] - 音量減少");
+    println!("  [n] - 下一首");
+    println!("  [p] - 上一首");
+    println!("  [r] - 切換重複模式（關閉/全部/單曲）");
+    println!("  [s] - 切換隨機播放");
     println!("  [q] - 退出");
     println!("=========================================\n");
 
     // 主控制循環
     loop {
+        // 先把解碼執行緒回報的狀態顯示出來
+        while let Ok(status) = status_rx.try_recv() {
+            match status {
+                PlayerStatus::Position(pos) => println!("位置: {}", format_time(pos)),
+                PlayerStatus::TrackChanged(name) => println!("\n▶ 正在播放: {}", name),
+                PlayerStatus::TrackInfo(info) => println!("{}\n", info),
+                PlayerStatus::Finished => println!("\n播放完成！"),
+                PlayerStatus::Error(msg) => eprintln!("⚠ {}", msg),
+            }
+        }
+
         // 检查播放线程是否已结束
         if play_thread.is_finished() {
-            println!("\n播放完成！");
-            break;
-        }
-        
-        if *player_stop.lock().unwrap() {
             break;
         }
 
@@ -302,47 +794,93 @@ fn main() {
                 if kind != KeyEventKind::Press {
                     continue;
                 }
-                
+
                 match code {
                     KeyCode::Char(' ') => {
-                        let mut paused = player_paused.lock().unwrap();
-                        *paused = !*paused;
-                        if *paused {
+                        paused = !paused;
+                        let cmd = if paused { PlayerCommand::Pause } else { PlayerCommand::Resume };
+                        cmd_tx.send(cmd).ok();
+                        if paused {
                             println!("⏸ 已暫停");
                         } else {
                             println!("▶ 繼續播放");
                         }
                     }
                     KeyCode::Left => {
-                        let current = *player_time.lock().unwrap();
-                        let new_position = (current - 5.0).max(0.0);
-                        let mut seek = player_seek.lock().unwrap();
-                        *seek = Some(new_position);
-                        println!("⏪ 後退 5 秒 (位置: {:.1}s)", new_position);
+                        cmd_tx.send(PlayerCommand::SeekRelative(-5.0)).ok();
+                        println!("⏪ 後退 5 秒");
                     }
                     KeyCode::Right => {
-                        let current = *player_time.lock().unwrap();
-                        let new_position = current + 5.0;
-                        let mut seek = player_seek.lock().unwrap();
-                        *seek = Some(new_position);
-                        println!("⏩ 前進 5 秒 (位置: {:.1}s)", new_position);
+                        cmd_tx.send(PlayerCommand::SeekRelative(5.0)).ok();
+                        println!("⏩ 前進 5 秒");
                     }
                     KeyCode::Up => {
-                        let mut vol = player_volume.lock().unwrap();
-                        *vol = (*vol + 0.1).min(2.0);
-                        println!("🔊 音量: {:.0}%", *vol * 100.0);
+                        volume_percent = (volume_percent + VOLUME_STEP).min(100);
+                        cmd_tx.send(PlayerCommand::SetVolume(volume_percent as f32)).ok();
+                        println!("🔊 音量: {}%", volume_percent);
                     }
                     KeyCode::Down => {
-                        let mut vol = player_volume.lock().unwrap();
-                        *vol = (*vol - 0.1).max(0.0);
-                        println!("🔉 音量: {:.0}%", *vol * 100.0);
+                        volume_percent = volume_percent.saturating_sub(VOLUME_STEP);
+                        cmd_tx.send(PlayerCommand::SetVolume(volume_percent as f32)).ok();
+                        println!("🔉 音量: {}%", volume_percent);
+                    }
+                    KeyCode::Char('n') | KeyCode::Char('N') => {
+                        cmd_tx.send(PlayerCommand::Next).ok();
+                        println!("⏭ 下一首");
+                    }
+                    KeyCode::Char('p') | KeyCode::Char('P') => {
+                        cmd_tx.send(PlayerCommand::Previous).ok();
+                        println!("⏮ 上一首");
+                    }
+                    KeyCode::Char('r') | KeyCode::Char('R') => {
+                        let mode = player_playlist.lock().unwrap().toggle_repeat();
+                        println!("🔁 重複模式: {}", mode.label());
+                    }
+                    KeyCode::Char('s') | KeyCode::Char('S') => {
+                        let shuffle = player_playlist.lock().unwrap().toggle_shuffle();
+                        println!("🔀 隨機播放: {}", if shuffle { "開啟" } else { "關閉" });
                     }
                     KeyCode::Char('q') | KeyCode::Char('Q') => {
                         println!("\n退出播放器...");
-                        let mut stop = player_stop.lock().unwrap();
-                        *stop = true;
+                        cmd_tx.send(PlayerCommand::Stop).ok();
                         break;
                     }
+                    // 耳機/鍵盤上的硬體媒體鍵：跟其他按鍵一樣從終端的按鍵事件讀入，
+                    // 只有終端把它們回報成按鍵事件時才會收到；終端沒有焦點時收不到
+                    KeyCode::Media(media) => match media {
+                        MediaKeyCode::Play | MediaKeyCode::Pause | MediaKeyCode::PlayPause => {
+                            paused = !paused;
+                            let cmd = if paused { PlayerCommand::Pause } else { PlayerCommand::Resume };
+                            cmd_tx.send(cmd).ok();
+                            if paused {
+                                println!("⏸ 已暫停（媒體鍵）");
+                            } else {
+                                println!("▶ 繼續播放（媒體鍵）");
+                            }
+                        }
+                        MediaKeyCode::Stop => {
+                            println!("\n退出播放器...");
+                            cmd_tx.send(PlayerCommand::Stop).ok();
+                            break;
+                        }
+                        MediaKeyCode::TrackNext => {
+                            cmd_tx.send(PlayerCommand::Next).ok();
+                            println!("⏭ 下一首（媒體鍵）");
+                        }
+                        MediaKeyCode::TrackPrevious => {
+                            cmd_tx.send(PlayerCommand::Previous).ok();
+                            println!("⏮ 上一首（媒體鍵）");
+                        }
+                        MediaKeyCode::FastForward => {
+                            cmd_tx.send(PlayerCommand::SeekRelative(5.0)).ok();
+                            println!("⏩ 前進 5 秒（媒體鍵）");
+                        }
+                        MediaKeyCode::Rewind => {
+                            cmd_tx.send(PlayerCommand::SeekRelative(-5.0)).ok();
+                            println!("⏪ 後退 5 秒（媒體鍵）");
+                        }
+                        _ => {}
+                    },
                     _ => {}
                 }
             }